@@ -14,11 +14,18 @@
 extern crate uucore;
 
 extern crate ini;
+#[cfg(unix)]
+extern crate libc;
+
+mod signals;
 
 use ini::Ini;
 use std::env;
+use std::io;
 use std::io::{stdin, stdout, Write};
-use std::process::Command;
+use std::process::{Command, ExitStatus};
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 
 static NAME: &str = "env";
 static SYNTAX: &str = "[OPTION]... [-] [NAME=VALUE]... [COMMAND [ARG]...]";
@@ -27,13 +34,31 @@ static LONG_HELP: &str = "
  A mere - implies -i. If no COMMAND, print the resulting environment
 ";
 
+// One piece of the COMMAND being assembled: either a literal argument, or
+// the raw text of a `-S`/`--split-string` argument that still needs to be
+// scanned. Splitting (and its `$VAR` expansion) is deferred until after
+// -i/-u/-f/sets have all been applied to the environment, per the order
+// `-S` documents.
+enum ProgramPart {
+    Literal(String),
+    Split(String),
+}
+
 struct Options {
     ignore_env: bool,
     null: bool,
     files: Vec<String>,
     unsets: Vec<String>,
     sets: Vec<(String, String)>,
-    program: Vec<String>,
+    program: Vec<ProgramPart>,
+    // `None` means the flag wasn't given; `Some("")` means it was given
+    // without an explicit LIST (meaning "every known signal").
+    ignore_signal: Option<String>,
+    default_signal: Option<String>,
+    block_signal: Option<String>,
+    list_signals: bool,
+    argv0: Option<String>,
+    expand: bool,
 }
 
 // print name=value env pairs on screen
@@ -44,8 +69,348 @@ fn print_env(null: bool) {
     }
 }
 
-fn split_string(s: &str) -> Vec<String> {
-    s.split_whitespace().map(|x| x.to_owned()).collect::<Vec<String>>()
+// Expands a single `$VAR`/`${VAR}` reference against the process
+// environment. `chars` is a char-indexed view of the string being scanned
+// (so every position is a real character, never a raw UTF-8 byte), and `ci`
+// is the index of the `$`; it is advanced past the whole reference.
+// Undefined variables expand to the empty string; a bare `$` not followed
+// by a name expands to itself.
+fn expand_dollar_ref(chars: &[(usize, char)], ci: &mut usize) -> String {
+    // chars[*ci].1 == '$'
+    *ci += 1;
+    let mut name = String::new();
+
+    if *ci < chars.len() && chars[*ci].1 == '{' {
+        *ci += 1;
+        while *ci < chars.len() && chars[*ci].1 != '}' {
+            name.push(chars[*ci].1);
+            *ci += 1;
+        }
+        if *ci < chars.len() {
+            *ci += 1; // consume '}'
+        }
+    } else {
+        while *ci < chars.len() && (chars[*ci].1.is_alphanumeric() || chars[*ci].1 == '_') {
+            name.push(chars[*ci].1);
+            *ci += 1;
+        }
+    }
+
+    if name.is_empty() {
+        "$".to_owned()
+    } else {
+        env::var(&name).unwrap_or_default()
+    }
+}
+
+// Expands `$VAR`/`${VAR}` references in a NAME=VALUE value (or a value
+// loaded from -f) against the current process environment, i.e. as mutated
+// so far by -i/-u/earlier sets. `\$` escapes a literal dollar sign; an
+// undefined variable expands to the empty string. Used behind --expand.
+fn expand_value(value: &str) -> String {
+    let chars: Vec<(usize, char)> = value.char_indices().collect();
+    let len = chars.len();
+    let mut result = String::new();
+    let mut ci = 0;
+
+    while ci < len {
+        match chars[ci].1 {
+            '\\' if ci + 1 < len && chars[ci + 1].1 == '$' => {
+                result.push('$');
+                ci += 2;
+            }
+            '$' => {
+                result.push_str(&expand_dollar_ref(&chars, &mut ci));
+            }
+            c => {
+                result.push(c);
+                ci += 1;
+            }
+        }
+    }
+
+    result
+}
+
+// Splits a `-S`/`--split-string` argument following GNU env's shebang-line
+// grammar: whitespace separates tokens, quotes take their contents literally
+// (double quotes still allow `$`-expansion and a handful of escapes), `\`
+// recognizes a small set of escapes outside of single quotes, `#` starts a
+// comment, and `$VAR`/`${VAR}` are expanded from the process environment.
+// Returns an error message (including the byte offset) on an unterminated
+// quote or an unrecognized escape.
+fn split_string(s: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let len = chars.len();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut have_token = false;
+    let mut ci = 0;
+
+    fn push_token(tokens: &mut Vec<String>, current: &mut String, have_token: &mut bool) {
+        if *have_token {
+            tokens.push(current.clone());
+            current.clear();
+            *have_token = false;
+        }
+    }
+
+    while ci < len {
+        let (byte_offset, c) = chars[ci];
+        match c {
+            ' ' | '\t' => {
+                push_token(&mut tokens, &mut current, &mut have_token);
+                ci += 1;
+            }
+            // Only begins a comment at a word boundary (string start or
+            // right after whitespace); mid-token it's a literal character.
+            '#' if ci == 0 || chars[ci - 1].1 == ' ' || chars[ci - 1].1 == '\t' => break,
+            '\\' => {
+                if ci + 1 >= len {
+                    return Err(format!("no terminating character found for escape at byte offset {}", byte_offset));
+                }
+                let esc = chars[ci + 1].1;
+                match esc {
+                    't' => {
+                        current.push('\t');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    'n' => {
+                        current.push('\n');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    'r' => {
+                        current.push('\r');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    'f' => {
+                        current.push('\x0C');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    'v' => {
+                        current.push('\x0B');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    '#' => {
+                        current.push('#');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    '$' => {
+                        current.push('$');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    '\\' => {
+                        current.push('\\');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    '_' => {
+                        current.push(' ');
+                        have_token = true;
+                        ci += 2;
+                    }
+                    'c' => {
+                        ci = len;
+                    }
+                    other => {
+                        return Err(format!("invalid backslash escape '\\{}' at byte offset {}", other, byte_offset));
+                    }
+                }
+            }
+            '\'' => {
+                have_token = true;
+                ci += 1;
+                let start = ci;
+                while ci < len && chars[ci].1 != '\'' {
+                    ci += 1;
+                }
+                if ci >= len {
+                    return Err(format!("no terminating quote found for single quote starting at byte offset {}", byte_offset));
+                }
+                for &(_, qc) in &chars[start..ci] {
+                    current.push(qc);
+                }
+                ci += 1;
+            }
+            '"' => {
+                have_token = true;
+                ci += 1;
+                let start_offset = byte_offset;
+                loop {
+                    if ci >= len {
+                        return Err(format!("no terminating quote found for double quote starting at byte offset {}", start_offset));
+                    }
+                    let qc = chars[ci].1;
+                    match qc {
+                        '"' => break,
+                        '\\' if ci + 1 < len && (chars[ci + 1].1 == '$' || chars[ci + 1].1 == '"' || chars[ci + 1].1 == '\\') => {
+                            current.push(chars[ci + 1].1);
+                            ci += 2;
+                        }
+                        '$' => {
+                            current.push_str(&expand_dollar_ref(&chars, &mut ci));
+                        }
+                        c => {
+                            current.push(c);
+                            ci += 1;
+                        }
+                    }
+                }
+                ci += 1; // consume closing quote
+            }
+            '$' => {
+                have_token = true;
+                current.push_str(&expand_dollar_ref(&chars, &mut ci));
+            }
+            _ => {
+                have_token = true;
+                current.push(c);
+                ci += 1;
+            }
+        }
+    }
+
+    push_token(&mut tokens, &mut current, &mut have_token);
+
+    Ok(tokens)
+}
+
+// Extracts the LIST out of "--option" or "--option=LIST"; an empty string
+// means "no explicit LIST was given", which the signal options take to mean
+// "every known signal".
+fn signal_list_arg(prefix: &str, name: &str) -> String {
+    if prefix.len() == name.len() {
+        String::new()
+    } else {
+        prefix[name.len() + 1..].to_owned()
+    }
+}
+
+#[cfg(unix)]
+fn signal_from_exit_status(exit: &ExitStatus) -> i32 {
+    exit.signal().unwrap_or(0)
+}
+
+#[cfg(not(unix))]
+fn signal_from_exit_status(_exit: &ExitStatus) -> i32 {
+    0
+}
+
+// The signal dispositions the child should apply to itself just before
+// exec'ing, derived from --ignore-signal/--default-signal/--block-signal.
+#[cfg(unix)]
+struct SignalDispositions {
+    default_signals: Vec<libc::c_int>,
+    ignore_signals: Vec<libc::c_int>,
+    block_signals: Vec<libc::c_int>,
+}
+
+#[cfg(not(unix))]
+struct SignalDispositions;
+
+#[cfg(unix)]
+fn resolve_signal_dispositions(opts: &Options) -> Result<SignalDispositions, String> {
+    fn resolve(spec: &Option<String>) -> Result<Vec<libc::c_int>, String> {
+        match spec {
+            None => Ok(vec![]),
+            Some(ref list) if list.is_empty() => Ok(signals::all_signals()),
+            Some(ref list) => signals::parse_signal_list(list),
+        }
+    }
+
+    let default_signals = resolve(&opts.default_signal)?;
+    let ignore_signals = resolve(&opts.ignore_signal)?;
+    let block_signals = resolve(&opts.block_signal)?;
+
+    // SIGKILL/SIGSTOP can't be caught, ignored, or defaulted; `resolve()`
+    // already drops them from the "every known signal" case, but an
+    // explicit `--ignore-signal=KILL` must still be rejected here, before
+    // we fork, rather than failing inside pre_exec after the child exists.
+    for sig in default_signals.iter().chain(ignore_signals.iter()) {
+        if !signals::is_settable(*sig) {
+            return Err(format!("failed to set signal action for signal {}", sig));
+        }
+    }
+
+    for sig in &ignore_signals {
+        if default_signals.contains(sig) {
+            return Err(format!(
+                "cannot both ignore and reset to default the same signal ({})",
+                sig
+            ));
+        }
+    }
+
+    Ok(SignalDispositions {
+        default_signals,
+        ignore_signals,
+        block_signals,
+    })
+}
+
+#[cfg(not(unix))]
+fn resolve_signal_dispositions(opts: &Options) -> Result<SignalDispositions, String> {
+    if opts.ignore_signal.is_some() || opts.default_signal.is_some() || opts.block_signal.is_some() {
+        return Err("signal options are not supported on this platform".to_owned());
+    }
+    Ok(SignalDispositions)
+}
+
+#[cfg(unix)]
+fn print_list_signals() -> i32 {
+    println!("{}", signals::list_signals());
+    0
+}
+
+#[cfg(not(unix))]
+fn print_list_signals() -> i32 {
+    eprintln!("{}: --list-signals is not supported on this platform", NAME);
+    1
+}
+
+// Applies the resolved signal dispositions in the child, right before exec.
+#[cfg(unix)]
+fn apply_signal_dispositions(dispositions: &SignalDispositions) -> io::Result<()> {
+    use std::mem;
+    use std::ptr;
+
+    unsafe {
+        for &sig in &dispositions.default_signals {
+            let mut sa: libc::sigaction = mem::zeroed();
+            sa.sa_sigaction = libc::SIG_DFL;
+            libc::sigemptyset(&mut sa.sa_mask);
+            if libc::sigaction(sig, &sa, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        for &sig in &dispositions.ignore_signals {
+            let mut sa: libc::sigaction = mem::zeroed();
+            sa.sa_sigaction = libc::SIG_IGN;
+            libc::sigemptyset(&mut sa.sa_mask);
+            if libc::sigaction(sig, &sa, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if !dispositions.block_signals.is_empty() {
+            let mut set: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            for &sig in &dispositions.block_signals {
+                libc::sigaddset(&mut set, sig);
+            }
+            if libc::sigprocmask(libc::SIG_BLOCK, &set, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(not(windows))]
@@ -59,6 +424,21 @@ fn build_command(mut args: Vec<String>) -> (String, Vec<String>) {
     (env::var("ComSpec").unwrap_or("cmd".to_string()), args)
 }
 
+// Sets the zeroth argument the child sees, as requested via -a/--argv0.
+// On unix this is genuinely distinct from the resolved executable path;
+// on Windows there is no equivalent, so the option is a documented no-op.
+#[cfg(unix)]
+fn apply_argv0(cmd: &mut Command, argv0: &Option<String>) {
+    if let Some(ref name) = *argv0 {
+        cmd.arg0(name);
+    }
+}
+
+#[cfg(not(unix))]
+fn apply_argv0(_cmd: &mut Command, _argv0: &Option<String>) {
+    // no-op: std::process::Command has no arg0 equivalent outside unix
+}
+
 pub fn uumain(args: Vec<String>) -> i32 {
     let mut core_opts = new_coreopts!(SYNTAX, SUMMARY, LONG_HELP);
     core_opts
@@ -74,7 +454,28 @@ pub fn uumain(args: Vec<String>) -> i32 {
             "process and split S into separate arguments; used to pass multiple arguments on shebang lines"
         )
         .optopt("f", "file", "read and sets variables from the file (prior to sets/unsets)", "FILE")
-        .optopt("u", "unset", "remove variable from the environment", "NAME");
+        .optopt("u", "unset", "remove variable from the environment", "NAME")
+        .optflagopt(
+            "",
+            "ignore-signal",
+            "set handling of signals in LIST to SIG_IGN, or all known signals if LIST is omitted",
+            "LIST",
+        )
+        .optflagopt(
+            "",
+            "default-signal",
+            "reset handling of signals in LIST to SIG_DFL, or all known signals if LIST is omitted",
+            "LIST",
+        )
+        .optflagopt(
+            "",
+            "block-signal",
+            "block delivery of signals in LIST, or all known signals if LIST is omitted",
+            "LIST",
+        )
+        .optflag("", "list-signals", "print a list of signal names known to this implementation and exit")
+        .optopt("a", "argv0", "pass NAME as the zeroth argument of COMMAND, instead of resolving it to the program name", "NAME")
+        .optflag("", "expand", "resolve $NAME/${NAME} references in NAME=VALUE and -f values against the environment built so far");
 
     let mut opts = Box::new(Options {
         ignore_env: false,
@@ -83,6 +484,12 @@ pub fn uumain(args: Vec<String>) -> i32 {
         files: vec![],
         sets: vec![],
         program: vec![],
+        ignore_signal: None,
+        default_signal: None,
+        block_signal: None,
+        list_signals: false,
+        argv0: None,
+        expand: false,
     });
 
     let mut wait_cmd = false;
@@ -108,7 +515,7 @@ pub fn uumain(args: Vec<String>) -> i32 {
                 }
                 _ => {
                     // read the program now
-                    opts.program.push(opt.to_owned());
+                    opts.program.push(ProgramPart::Literal(opt.to_owned()));
                     break;
                 }
             }
@@ -124,6 +531,7 @@ pub fn uumain(args: Vec<String>) -> i32 {
                 }
 
                 "--ignore-environment" => opts.ignore_env = true,
+                "--expand" => opts.expand = true,
                 "--null" => opts.null = true,
                 "--file" => {
                     let var = iter.next();
@@ -141,17 +549,40 @@ pub fn uumain(args: Vec<String>) -> i32 {
                         Some(s) => opts.unsets.push(s.to_owned()),
                     }
                 }
+                "--argv0" => {
+                    let var = iter.next();
+
+                    match var {
+                        None => eprintln!("{}: this option requires an argument: {}", NAME, opt),
+                        Some(s) => opts.argv0 = Some(s.to_owned()),
+                    }
+                }
+                prefix if prefix.starts_with("--argv0=") => {
+                    opts.argv0 = Some(prefix["--argv0=".len()..].to_owned());
+                }
+                "--list-signals" => {
+                    opts.list_signals = true;
+                }
+                prefix if prefix.starts_with("--ignore-signal") => {
+                    opts.ignore_signal = Some(signal_list_arg(prefix, "--ignore-signal"));
+                }
+                prefix if prefix.starts_with("--default-signal") => {
+                    opts.default_signal = Some(signal_list_arg(prefix, "--default-signal"));
+                }
+                prefix if prefix.starts_with("--block-signal") => {
+                    opts.block_signal = Some(signal_list_arg(prefix, "--block-signal"));
+                }
                 prefix if prefix.starts_with("--split-string") => {
                     let length = "--split-string".len();
                     if prefix.len() == length { // when used like "env --split-string 'foo bar'"
                         let string = iter.next();
                         match string {
                             None => eprintln!("{}: this option requires an argument: {}", NAME, opt),
-                            Some(s) => opts.program.append(&mut split_string(s)) ,
+                            Some(s) => opts.program.push(ProgramPart::Split(s.to_owned())),
                         }
 
                     } else { // everything is passed as one argument (typical for shebang)
-                        opts.program.append(&mut split_string(opt[length..].trim()));
+                        opts.program.push(ProgramPart::Split(opt[length..].trim().to_owned()));
                     }
 
                 }
@@ -176,11 +607,11 @@ pub fn uumain(args: Vec<String>) -> i32 {
                     let string = iter.next();
                     match string {
                         None => eprintln!("{}: this option requires an argument: {}", NAME, opt),
-                        Some(s) => opts.program.append(&mut split_string(s)) ,
+                        Some(s) => opts.program.push(ProgramPart::Split(s.to_owned())),
                     }
 
                 } else { // everything is passed as one argument, typical for shebang
-                    opts.program.append(&mut split_string(opt[2..].trim()));
+                    opts.program.push(ProgramPart::Split(opt[2..].trim().to_owned()));
                 }
 
             } else {
@@ -209,6 +640,14 @@ pub fn uumain(args: Vec<String>) -> i32 {
                                 Some(s) => opts.unsets.push(s.to_owned()),
                             }
                         }
+                        'a' => {
+                            let var = iter.next();
+
+                            match var {
+                                None => eprintln!("{}: this option requires an argument: {}", NAME, opt),
+                                Some(s) => opts.argv0 = Some(s.to_owned()),
+                            }
+                        }
                         _ => {
                             eprintln!("{}: illegal option -- {}", NAME, c);
                             eprintln!("Type \"{} --help\" for detailed information", NAME);
@@ -238,7 +677,7 @@ pub fn uumain(args: Vec<String>) -> i32 {
                         eprintln!("Type \"{} --help\" for detailed information", NAME);
                         return 1;
                     }
-                    opts.program.push(opt.clone());
+                    opts.program.push(ProgramPart::Literal(opt.clone()));
                     break;
                 }
             }
@@ -254,9 +693,21 @@ pub fn uumain(args: Vec<String>) -> i32 {
             eprintln!("Type \"{} --help\" for detailed information", NAME);
             return 1;
         }
-        opts.program.push(opt.clone())
+        opts.program.push(ProgramPart::Literal(opt.clone()))
     }
 
+    if opts.list_signals {
+        return print_list_signals();
+    }
+
+    let signal_dispositions = match resolve_signal_dispositions(&opts) {
+        Ok(dispositions) => dispositions,
+        Err(msg) => {
+            eprintln!("{}: {}", NAME, msg);
+            return 125;
+        }
+    };
+
     if opts.ignore_env {
         for (ref name, _) in env::vars() {
             env::remove_var(name);
@@ -280,7 +731,11 @@ pub fn uumain(args: Vec<String>) -> i32 {
         };
         for (_, prop) in &conf {
             for (key, value) in prop {
-                env::set_var(key, value);
+                if opts.expand {
+                    env::set_var(key, expand_value(value));
+                } else {
+                    env::set_var(key, value);
+                }
             }
         }
     }
@@ -290,20 +745,71 @@ pub fn uumain(args: Vec<String>) -> i32 {
     }
 
     for &(ref name, ref val) in &opts.sets {
-        env::set_var(name, val);
+        if opts.expand {
+            env::set_var(name, expand_value(val));
+        } else {
+            env::set_var(name, val);
+        }
     }
 
-    if !opts.program.is_empty() {
-        let (prog, args) = build_command(opts.program);
-        match Command::new(prog).args(args).status() {
+    // Only now that -i/-u/-f/sets have all been applied do we split any
+    // -S/--split-string argument, so its $VAR references resolve against
+    // the environment as mutated above rather than the inherited one.
+    let mut program: Vec<String> = Vec::new();
+    for part in opts.program {
+        match part {
+            ProgramPart::Literal(s) => program.push(s),
+            ProgramPart::Split(raw) => match split_string(&raw) {
+                Ok(mut tokens) => program.append(&mut tokens),
+                Err(msg) => {
+                    eprintln!("{}: {}", NAME, msg);
+                    return 125;
+                }
+            },
+        }
+    }
+
+    if opts.argv0.is_some() && program.is_empty() {
+        eprintln!("{}: cannot specify --argv0 (-a) without a command", NAME);
+        return 125;
+    }
+
+    if !program.is_empty() {
+        let (prog, args) = build_command(program);
+        let mut cmd = Command::new(&prog);
+        cmd.args(args);
+        apply_argv0(&mut cmd, &opts.argv0);
+        #[cfg(unix)]
+        unsafe {
+            cmd.pre_exec(move || {
+                apply_signal_dispositions(&signal_dispositions)
+            });
+        }
+        #[cfg(not(unix))]
+        let _ = &signal_dispositions;
+        match cmd.status() {
             Ok(exit) => {
                 return if exit.success() {
                     0
                 } else {
-                    exit.code().unwrap()
+                    match exit.code() {
+                        Some(code) => code,
+                        // terminated by signal N: conventional 128+N exit status
+                        None => 128 + signal_from_exit_status(&exit),
+                    }
                 }
             }
-            Err(_) => return 1,
+            Err(e) => {
+                let status = match e.kind() {
+                    io::ErrorKind::NotFound => 127,
+                    io::ErrorKind::PermissionDenied => 126,
+                    // some other failure to spawn (not a not-found/not-executable
+                    // case): treat as a failure of env itself
+                    _ => 125,
+                };
+                eprintln!("{}: '{}': {}", NAME, prog, e);
+                return status;
+            }
         }
     } else {
         // no program provided
@@ -0,0 +1,112 @@
+/*
+ * This file is part of the uutils coreutils package.
+ *
+ * (c) Jordi Boggiano <j.boggiano@seld.be>
+ *
+ * For the full copyright and license information, please view the LICENSE
+ * file that was distributed with this source code.
+ */
+
+// The set of signals `env` lets a caller reset to SIG_DFL, set to SIG_IGN,
+// or add to the blocked mask before exec'ing the child (see --ignore-signal,
+// --default-signal, --block-signal, --list-signals).
+#[cfg(unix)]
+pub static SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+// Formats the known signal table, one per line as "NUM) SIGNAME", matching
+// GNU env's `--list-signals` output.
+#[cfg(unix)]
+pub fn list_signals() -> String {
+    SIGNALS
+        .iter()
+        .map(|&(name, num)| format!("{}) SIG{}", num, name))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Looks a single signal up by name (with or without the "SIG" prefix,
+// case-insensitively) or by its numeric value.
+#[cfg(unix)]
+pub fn lookup_signal(spec: &str) -> Option<libc::c_int> {
+    if let Ok(num) = spec.parse::<libc::c_int>() {
+        if SIGNALS.iter().any(|&(_, n)| n == num) {
+            return Some(num);
+        }
+        return None;
+    }
+
+    let name = match spec.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("SIG") => &spec[3..],
+        _ => spec,
+    };
+    SIGNALS
+        .iter()
+        .find(|&&(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, num)| num)
+}
+
+// Parses a comma-separated list of signal names/numbers, e.g. "PIPE,INT" or
+// "13,SIGINT". An empty entry or an unrecognized name is an error.
+#[cfg(unix)]
+pub fn parse_signal_list(list: &str) -> Result<Vec<libc::c_int>, String> {
+    let mut signals = Vec::new();
+    for part in list.split(',') {
+        if part.is_empty() {
+            return Err(format!("invalid signal \"{}\"", list));
+        }
+        match lookup_signal(part) {
+            Some(num) => signals.push(num),
+            None => return Err(format!("{}: invalid signal", part)),
+        }
+    }
+    Ok(signals)
+}
+
+// SIGKILL/SIGSTOP cannot be caught, ignored, or reset via sigaction (EINVAL);
+// GNU env's "every known signal" expansion silently skips them.
+#[cfg(unix)]
+pub fn is_settable(sig: libc::c_int) -> bool {
+    sig != libc::SIGKILL && sig != libc::SIGSTOP
+}
+
+// Returns every known signal except SIGKILL/SIGSTOP, used when
+// --ignore-signal/--default-signal/--block-signal are given without an
+// explicit LIST.
+#[cfg(unix)]
+pub fn all_signals() -> Vec<libc::c_int> {
+    SIGNALS
+        .iter()
+        .map(|&(_, num)| num)
+        .filter(|&num| is_settable(num))
+        .collect()
+}